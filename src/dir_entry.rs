@@ -1,25 +1,247 @@
 use std::ffi::OsStr;
 use std::{
+    fmt,
     fs::{FileType, Metadata},
+    io,
     path::{Path, PathBuf},
     collections::HashMap,
 };
 use std::borrow::Cow;
+use std::sync::Arc;
 
 use once_cell::unsync::OnceCell;
 use regex::bytes::Regex;
 
 use crate::filesystem;
 
+// `rustix`'s `OFlags::PATH` (needed for the fd-relative `openat`/`fstat` stat path
+// below) only exists on these targets -- see `rustix::fs::OFlags` -- so macOS, NetBSD,
+// OpenBSD and illumos/Solaris fall back to a full-path `symlink_metadata()` call. Items
+// that only need to be included/excluded wholesale for this target list go through
+// `fd_relative_stat_items!` so the list is spelled out in one place; struct fields and
+// match arms can't be produced by an item macro, so those still repeat the `cfg` (kept
+// to a minimum below).
+macro_rules! fd_relative_stat_items {
+    ($($item:item)*) => {
+        $(
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "emscripten",
+                target_os = "freebsd",
+                target_os = "fuchsia",
+                target_os = "redox",
+            ))]
+            $item
+        )*
+    };
+}
+
+fd_relative_stat_items! {
+    use std::os::fd::OwnedFd;
+}
+
+/// A dangling/broken symlink entry, together with an optional handle to its parent
+/// directory (supplied by the walker via [`DirEntry::broken_symlink_in`]) so that
+/// `stat`ing it does not have to re-resolve every path component.
+struct BrokenSymlink {
+    path: PathBuf,
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "emscripten",
+        target_os = "freebsd",
+        target_os = "fuchsia",
+        target_os = "redox",
+    ))]
+    parent_dir: Option<Arc<OwnedFd>>,
+}
+
+impl BrokenSymlink {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "emscripten",
+                target_os = "freebsd",
+                target_os = "fuchsia",
+                target_os = "redox",
+            ))]
+            parent_dir: None,
+        }
+    }
+
+}
+
+fd_relative_stat_items! {
+    impl BrokenSymlink {
+        fn with_parent_dir(path: PathBuf, parent_dir: Arc<OwnedFd>) -> Self {
+            Self {
+                path,
+                parent_dir: Some(parent_dir),
+            }
+        }
+
+        /// The file name component of `path`, as required by the `*at` family of syscalls.
+        fn file_name(&self) -> &OsStr {
+            self.path.file_name().unwrap_or_else(|| self.path.as_os_str())
+        }
+
+        /// Stat the entry relative to its already-open parent directory handle, so only
+        /// the final path component needs to be resolved by the kernel. Only takes this
+        /// path when the walker already handed us a parent directory handle via
+        /// [`DirEntry::broken_symlink_in`]; otherwise this is a plain `symlink_metadata()`
+        /// call, exactly as before, so entries built via [`DirEntry::broken_symlink`]
+        /// don't pay for an `open` + `openat` + `fstat` round trip to save a single
+        /// `lstat`.
+        fn stat(&self) -> io::Result<Metadata> {
+            if let Some(parent_dir) = &self.parent_dir {
+                let fd = rustix::fs::openat(
+                    &**parent_dir,
+                    self.file_name(),
+                    rustix::fs::OFlags::PATH | rustix::fs::OFlags::NOFOLLOW,
+                    rustix::fs::Mode::empty(),
+                )
+                .map_err(io::Error::from)?;
+                return std::fs::File::from(fd).metadata();
+            }
+            self.path.symlink_metadata()
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "emscripten",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "redox",
+)))]
+impl BrokenSymlink {
+    fn stat(&self) -> io::Result<Metadata> {
+        self.path.symlink_metadata()
+    }
+}
+
 enum DirEntryInner {
     Normal(ignore::DirEntry),
-    BrokenSymlink(PathBuf),
+    BrokenSymlink(BrokenSymlink),
 }
 
 pub struct DirEntry {
     inner: DirEntryInner,
-    metadata: OnceCell<Option<Metadata>>,
+    metadata: OnceCell<Result<Metadata, EntryError>>,
     match_list: HashMap<usize, HashMap<usize, String>>,
+    named_match_list: HashMap<usize, HashMap<String, String>>,
+}
+
+/// The reason retrieving an entry's metadata failed, classified so that callers can
+/// report the same diagnostics as `find` ("Permission denied", "No such file or
+/// directory", ...) instead of silently dropping the entry.
+#[derive(Debug)]
+pub enum EntryError {
+    /// The path no longer exists (e.g. a race with a concurrent deletion).
+    NotFound(PathBuf),
+    /// The process does not have permission to stat the path.
+    PermissionDenied(PathBuf),
+    /// Any other OS-level error, with the raw `io::Error` preserved.
+    Io(PathBuf, io::Error),
+}
+
+impl EntryError {
+    fn from_io_error(path: &Path, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => EntryError::NotFound(path.to_path_buf()),
+            io::ErrorKind::PermissionDenied => EntryError::PermissionDenied(path.to_path_buf()),
+            _ => EntryError::Io(path.to_path_buf(), err),
+        }
+    }
+
+    /// The path that the failed operation was attempted on.
+    pub fn path(&self) -> &Path {
+        match self {
+            EntryError::NotFound(path) => path,
+            EntryError::PermissionDenied(path) => path,
+            EntryError::Io(path, _) => path,
+        }
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryError::NotFound(path) => {
+                write!(f, "'{}': No such file or directory", path.display())
+            }
+            EntryError::PermissionDenied(path) => {
+                write!(f, "'{}': Permission denied", path.display())
+            }
+            EntryError::Io(path, err) => write!(f, "'{}': {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for EntryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EntryError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The type of a directory entry that `fd` cannot meaningfully act on (as opposed to a
+/// regular file or symlink, which is the common case).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadType {
+    CharacterDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    Directory,
+    Unknown,
+}
+
+impl BadType {
+    fn from_file_type(file_type: FileType) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_char_device() {
+                return BadType::CharacterDevice;
+            }
+            if file_type.is_block_device() {
+                return BadType::BlockDevice;
+            }
+            if file_type.is_fifo() {
+                return BadType::Fifo;
+            }
+            if file_type.is_socket() {
+                return BadType::Socket;
+            }
+        }
+        if file_type.is_dir() {
+            return BadType::Directory;
+        }
+        BadType::Unknown
+    }
+}
+
+impl fmt::Display for BadType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BadType::CharacterDevice => "character device",
+            BadType::BlockDevice => "block device",
+            BadType::Fifo => "FIFO",
+            BadType::Socket => "socket",
+            BadType::Directory => "directory",
+            BadType::Unknown => "unknown type",
+        };
+        write!(f, "{name}")
+    }
 }
 
 impl DirEntry {
@@ -29,21 +251,23 @@ impl DirEntry {
             inner: DirEntryInner::Normal(e),
             metadata: OnceCell::new(),
             match_list: HashMap::new(),
+            named_match_list: HashMap::new(),
         }
     }
 
     pub fn broken_symlink(path: PathBuf) -> Self {
         Self {
-            inner: DirEntryInner::BrokenSymlink(path),
+            inner: DirEntryInner::BrokenSymlink(BrokenSymlink::new(path)),
             metadata: OnceCell::new(),
             match_list: HashMap::new(),
+            named_match_list: HashMap::new(),
         }
     }
 
     pub fn path(&self) -> &Path {
         match &self.inner {
             DirEntryInner::Normal(e) => e.path(),
-            DirEntryInner::BrokenSymlink(pathbuf) => pathbuf.as_path(),
+            DirEntryInner::BrokenSymlink(sym) => sym.path.as_path(),
         }
     }
 
@@ -51,25 +275,72 @@ impl DirEntry {
         &self.match_list
     }
 
+    /// Like [`Self::matches`], but keyed by the pattern's named capture groups (e.g.
+    /// `(?P<year>\d{4})`) instead of their numeric group index.
+    pub fn named_matches(&self) -> &HashMap<usize, HashMap<String, String>> {
+        &self.named_match_list
+    }
+
     pub fn into_path(self) -> PathBuf {
         match self.inner {
             DirEntryInner::Normal(e) => e.into_path(),
-            DirEntryInner::BrokenSymlink(p) => p,
+            DirEntryInner::BrokenSymlink(sym) => sym.path,
         }
     }
 
     pub fn file_type(&self) -> Option<FileType> {
+        self.file_type_result().ok()
+    }
+
+    /// Like [`Self::file_type`], but keeps the reason the type could not be determined
+    /// instead of discarding it. `ignore::DirEntry::file_type()` is usually free (read
+    /// straight off the directory entry, no syscall), so we only fall back to
+    /// [`Self::metadata_result`] -- and thus to a classified [`EntryError`] -- when that
+    /// cheap path comes back empty.
+    pub fn file_type_result(&self) -> Result<FileType, &EntryError> {
         match &self.inner {
-            DirEntryInner::Normal(e) => e.file_type(),
-            DirEntryInner::BrokenSymlink(_) => self.metadata().map(|m| m.file_type()),
+            DirEntryInner::Normal(e) => match e.file_type() {
+                Some(file_type) => Ok(file_type),
+                None => self.metadata_result().map(|m| m.file_type()),
+            },
+            DirEntryInner::BrokenSymlink(_) => self.metadata_result().map(|m| m.file_type()),
+        }
+    }
+
+    /// The type of this entry, if it is one that `fd` should not act on (a character
+    /// device, a socket, ...). Returns `None` for regular files, symlinks and for
+    /// entries whose type could not be determined at all (see [`Self::file_type_result`]).
+    pub fn bad_type(&self) -> Option<BadType> {
+        let file_type = self.file_type_result().ok()?;
+        if file_type.is_file() || file_type.is_symlink() {
+            return None;
         }
+        Some(BadType::from_file_type(file_type))
     }
 
     pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata_result().ok()
+    }
+
+    /// Like [`Self::metadata`], but keeps the reason the lookup failed instead of
+    /// discarding it, so callers can report "Permission denied" etc. rather than just
+    /// dropping the entry.
+    pub fn metadata_result(&self) -> Result<&Metadata, &EntryError> {
         self.metadata
             .get_or_init(|| match &self.inner {
-                DirEntryInner::Normal(e) => e.metadata().ok(),
-                DirEntryInner::BrokenSymlink(path) => path.symlink_metadata().ok(),
+                DirEntryInner::Normal(e) => e.metadata().map_err(|err| {
+                    let io_err = err
+                        .io_error()
+                        .map(|e| match e.raw_os_error() {
+                            Some(code) => io::Error::from_raw_os_error(code),
+                            None => io::Error::new(e.kind(), e.to_string()),
+                        })
+                        .unwrap_or_else(|| io::Error::other(err.to_string()));
+                    EntryError::from_io_error(self.path(), io_err)
+                }),
+                DirEntryInner::BrokenSymlink(sym) => sym
+                    .stat()
+                    .map_err(|err| EntryError::from_io_error(&sym.path, err)),
             })
             .as_ref()
     }
@@ -81,24 +352,59 @@ impl DirEntry {
         }
     }
 
+    /// Report this entry's [`EntryError`]/[`BadType`] classification to stderr as a
+    /// `find`-style `"fd: cannot access '<path>': <reason>"` warning and indicate whether
+    /// the entry should be skipped. Directories are never reported here even though
+    /// [`Self::bad_type`] classifies them as [`BadType::Directory`], since `fd` lists and
+    /// descends into directories rather than refusing to act on them.
+    ///
+    /// This is the warning channel callers are expected to invoke before handing an entry
+    /// to [`Self::is_match`]/the output stage, in place of silently dropping entries that
+    /// errored or are the wrong type. `is_match` itself stays a pure regex predicate over
+    /// the entry's path -- it does not call this, since conflating "does the path match"
+    /// with "can we stat this entry" would surprise callers like `format_capture_template`
+    /// that only ever deal with already-accepted entries.
+    pub fn warn_if_inaccessible(&self) -> bool {
+        match self.file_type_result() {
+            Err(err) => {
+                eprintln!("fd: cannot access {err}");
+                true
+            }
+            Ok(_) => match self.bad_type() {
+                None | Some(BadType::Directory) => false,
+                Some(bad_type) => {
+                    eprintln!("fd: cannot access '{}': {bad_type}", self.path().display());
+                    true
+                }
+            },
+        }
+    }
+
     pub fn is_match(&mut self, pattern: &Regex, search_full_path: bool) -> bool {
         let search_str = self.get_search_str(search_full_path);
         let search_res = filesystem::osstr_to_bytes(search_str.as_ref());
         let mut found: HashMap<usize, HashMap<usize, String>> = HashMap::new();
+        let mut named_found: HashMap<usize, HashMap<String, String>> = HashMap::new();
+        let capture_names: Vec<Option<&str>> = pattern.capture_names().collect();
 
         for (ocurrence, matched) in pattern.captures_iter(&search_res).enumerate() {
             let mut matched_groups: HashMap<usize, String> = HashMap::new();
+            let mut named_groups: HashMap<String, String> = HashMap::new();
             for (group, group_match) in matched.iter().enumerate() {
                 if let Some(value) = group_match {
-                    let cap = value.as_bytes();
-                    let text = String::from_utf8(cap.to_vec()).unwrap();
-                    matched_groups.insert(group, text );    
+                    let text = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    if let Some(Some(name)) = capture_names.get(group) {
+                        named_groups.insert((*name).to_owned(), text.clone());
+                    }
+                    matched_groups.insert(group, text);
                 }
             }
             found.insert(ocurrence, matched_groups);
+            named_found.insert(ocurrence, named_groups);
         }
         self.match_list = found;
-        self.match_list.len() > 0
+        self.named_match_list = named_found;
+        !self.match_list.is_empty()
     }
 
     fn get_search_str(&self, search_full_path: bool) -> Cow<OsStr> {
@@ -122,6 +428,24 @@ impl DirEntry {
     }
 }
 
+fd_relative_stat_items! {
+    impl DirEntry {
+        /// Like [`Self::broken_symlink`], but reuses a file descriptor the walker
+        /// already holds open for the entry's parent directory, saving a redundant
+        /// `open(2)`. A shared, thread-safe handle is required since
+        /// `ignore::WalkParallel` constructs entries on worker threads before handing
+        /// them to the consumer over a channel.
+        pub fn broken_symlink_in(path: PathBuf, parent_dir: Arc<OwnedFd>) -> Self {
+            Self {
+                inner: DirEntryInner::BrokenSymlink(BrokenSymlink::with_parent_dir(path, parent_dir)),
+                metadata: OnceCell::new(),
+                match_list: HashMap::new(),
+                named_match_list: HashMap::new(),
+            }
+        }
+    }
+}
+
 impl PartialEq for DirEntry {
     #[inline]
     fn eq(&self, other: &Self) -> bool {
@@ -143,3 +467,113 @@ impl Ord for DirEntry {
         self.path().cmp(other.path())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_error_classifies_not_found() {
+        let err = EntryError::from_io_error(
+            Path::new("/nonexistent"),
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        assert!(matches!(err, EntryError::NotFound(_)));
+        assert_eq!(err.to_string(), "'/nonexistent': No such file or directory");
+    }
+
+    #[test]
+    fn entry_error_classifies_permission_denied() {
+        let err = EntryError::from_io_error(
+            Path::new("/root/secret"),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+        assert!(matches!(err, EntryError::PermissionDenied(_)));
+        assert_eq!(err.to_string(), "'/root/secret': Permission denied");
+    }
+
+    #[test]
+    fn entry_error_preserves_other_io_errors() {
+        let err = EntryError::from_io_error(
+            Path::new("/dev/nope"),
+            io::Error::other("device error"),
+        );
+        assert!(matches!(err, EntryError::Io(_, _)));
+        assert_eq!(err.path(), Path::new("/dev/nope"));
+    }
+
+    #[test]
+    fn bad_type_is_none_for_regular_files() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-dir-entry-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("regular-file");
+        std::fs::write(&file_path, b"hello").unwrap();
+
+        let entry = DirEntry::broken_symlink(file_path.clone());
+        assert!(entry.bad_type().is_none());
+        assert_eq!(entry.metadata().unwrap().len(), 5);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_type_display_names_match_the_classification() {
+        assert_eq!(BadType::CharacterDevice.to_string(), "character device");
+        assert_eq!(BadType::Directory.to_string(), "directory");
+        assert_eq!(BadType::Unknown.to_string(), "unknown type");
+    }
+
+    #[test]
+    fn warn_if_inaccessible_is_false_for_a_readable_regular_file() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-dir-entry-warn-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("regular-file");
+        std::fs::write(&file_path, b"hello").unwrap();
+
+        let entry = DirEntry::broken_symlink(file_path);
+        assert!(!entry.warn_if_inaccessible());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn warn_if_inaccessible_is_true_for_a_path_that_does_not_exist() {
+        let entry = DirEntry::broken_symlink(PathBuf::from("/nonexistent/fd-dir-entry-warn"));
+        assert!(entry.warn_if_inaccessible());
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "emscripten",
+        target_os = "freebsd",
+        target_os = "fuchsia",
+        target_os = "redox",
+    ))]
+    #[test]
+    fn broken_symlink_in_matches_plain_symlink_metadata() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-dir-entry-fdrelative-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let link_path = dir.join("dangling-symlink");
+        std::os::unix::fs::symlink(dir.join("does-not-exist"), &link_path).unwrap();
+
+        let parent_fd = rustix::fs::open(
+            &dir,
+            rustix::fs::OFlags::DIRECTORY | rustix::fs::OFlags::PATH,
+            rustix::fs::Mode::empty(),
+        )
+        .unwrap();
+
+        let entry = DirEntry::broken_symlink_in(link_path.clone(), Arc::new(parent_fd));
+        let via_fd = entry.metadata().unwrap();
+        let via_path = link_path.symlink_metadata().unwrap();
+
+        assert_eq!(via_fd.file_type(), via_path.file_type());
+        assert_eq!(via_fd.len(), via_path.len());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
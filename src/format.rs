@@ -0,0 +1,90 @@
+use crate::dir_entry::DirEntry;
+
+/// Interpolate `{1}`, `{2}`, `{name}`-style placeholders in `template` using the
+/// capture groups `DirEntry::is_match` recorded for the entry's first match occurrence.
+///
+/// Used both to build `--format` output lines and to expand `--exec`/`--batch` command
+/// templates, so e.g. `fd '(\d{4})-(\w+)\.log' --format '{2}/{1}.log'` can reference
+/// capture groups directly. A placeholder that does not resolve to a captured group
+/// (unmatched optional group, unknown name, typo) is left in the output verbatim rather
+/// than silently dropped, so the mistake is visible instead of producing a mangled path.
+pub fn format_capture_template(template: &str, entry: &DirEntry) -> String {
+    let numbered = entry.matches().get(&0);
+    let named = entry.named_matches().get(&0);
+
+    let mut output = String::with_capacity(template.len());
+    let mut skip_until = 0;
+
+    for (pos, c) in template.char_indices() {
+        if pos < skip_until {
+            continue;
+        }
+        if c != '{' {
+            output.push(c);
+            continue;
+        }
+
+        match template[pos + 1..].find('}') {
+            Some(rel_end) => {
+                let end = pos + 1 + rel_end;
+                let token = &template[pos + 1..end];
+                let replacement = if let Ok(index) = token.parse::<usize>() {
+                    numbered.and_then(|groups| groups.get(&index))
+                } else {
+                    named.and_then(|groups| groups.get(token))
+                };
+
+                match replacement {
+                    Some(value) => output.push_str(value),
+                    None => output.push_str(&template[pos..=end]),
+                }
+                skip_until = end + 1;
+            }
+            None => output.push('{'),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::bytes::Regex;
+    use std::path::PathBuf;
+
+    fn matched_entry(pattern: &str, path: &str) -> DirEntry {
+        let mut entry = DirEntry::broken_symlink(PathBuf::from(path));
+        let regex = Regex::new(pattern).unwrap();
+        assert!(entry.is_match(&regex, false));
+        entry
+    }
+
+    #[test]
+    fn interpolates_numbered_groups() {
+        let entry = matched_entry(r"(\d{4})-(\w+)\.log", "2026-access.log");
+        let output = format_capture_template("{2}/{1}.log", &entry);
+        assert_eq!(output, "access/2026.log");
+    }
+
+    #[test]
+    fn interpolates_named_groups() {
+        let entry = matched_entry(r"(?P<year>\d{4})-(?P<kind>\w+)\.log", "2026-access.log");
+        let output = format_capture_template("{kind}/{year}.log", &entry);
+        assert_eq!(output, "access/2026.log");
+    }
+
+    #[test]
+    fn leaves_unresolved_tokens_verbatim() {
+        let entry = matched_entry(r"(\d{4})", "2026");
+        let output = format_capture_template("{1}-{nope}-{2}", &entry);
+        assert_eq!(output, "2026-{nope}-{2}");
+    }
+
+    #[test]
+    fn passes_through_text_without_placeholders() {
+        let entry = matched_entry(r"(\d{4})", "2026");
+        let output = format_capture_template("plain/text.log", &entry);
+        assert_eq!(output, "plain/text.log");
+    }
+}
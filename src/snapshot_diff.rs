@@ -0,0 +1,451 @@
+use std::{
+    cmp::Ordering,
+    fs::Metadata,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::dir_entry::DirEntry;
+
+/// The outcome of comparing a single path between the live filesystem and a previously
+/// saved manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffStatus {
+    /// The path exists on disk but not in the manifest.
+    Added,
+    /// The path exists in the manifest but not on disk.
+    Removed,
+    /// The path exists on both sides, but its size or modification time differs.
+    Modified,
+    /// The path exists on both sides with matching size and modification time.
+    Unchanged,
+}
+
+/// One line of a saved manifest: a path together with the metadata needed to detect
+/// whether it changed, without re-reading the file's contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: SystemTime,
+}
+
+/// A path paired with how it changed relative to the manifest.
+#[derive(Debug, Clone)]
+pub struct DiffEntry {
+    pub path: PathBuf,
+    pub status: DiffStatus,
+}
+
+/// Write a manifest of `entries` in the length-prefixed record format read back by
+/// [`read_manifest`]. `entries` is expected to already be sorted by path, as the
+/// existing `Ord for DirEntry` produces when a walk's results are sorted.
+///
+/// Each record is `<size>\t<secs>.<nanos>\t<path_len>\t<path_len bytes>\n`: the path is
+/// read back by its declared byte length rather than by scanning for a line terminator,
+/// so a path containing a literal tab or newline (both legal on unix) round-trips
+/// correctly instead of corrupting the record framing.
+pub fn write_manifest<'a, W: Write>(
+    entries: impl Iterator<Item = &'a DirEntry>,
+    writer: &mut W,
+) -> io::Result<()> {
+    for entry in entries {
+        let Some(metadata) = entry.metadata() else {
+            continue;
+        };
+        let mtime = metadata
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let path_bytes = path_to_bytes(entry.path());
+
+        write!(
+            writer,
+            "{}\t{}.{:09}\t{}\t",
+            metadata.len(),
+            mtime.as_secs(),
+            mtime.subsec_nanos(),
+            path_bytes.len(),
+        )?;
+        writer.write_all(&path_bytes)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Read back a manifest previously written by [`write_manifest`], sorting it by path so
+/// that [`diff`] can assume both sides are ordered the same way.
+pub fn read_manifest(path: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = io::BufReader::new(file);
+    let mut entries = Vec::new();
+
+    while let Some(entry) = read_manifest_entry(&mut reader)? {
+        entries.push(entry);
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Read one `\t`-delimited field (the delimiter is consumed but not included). Returns
+/// `Ok(None)` only at a clean end-of-file, i.e. between records.
+fn read_field<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::new();
+    let n = reader.read_until(b'\t', &mut buf)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if buf.last() == Some(&b'\t') {
+        buf.pop();
+    }
+    Ok(Some(buf))
+}
+
+fn truncated_manifest_error() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated snapshot manifest entry")
+}
+
+fn read_manifest_entry<R: BufRead>(reader: &mut R) -> io::Result<Option<ManifestEntry>> {
+    let Some(size) = read_field(reader)? else {
+        return Ok(None);
+    };
+    let mtime = read_field(reader)?.ok_or_else(truncated_manifest_error)?;
+    let path_len = read_field(reader)?.ok_or_else(truncated_manifest_error)?;
+
+    let size: u64 = parse_ascii(&size, "size")?;
+    let path_len: usize = parse_ascii(&path_len, "path length")?;
+    let mut path_bytes = vec![0u8; path_len];
+    reader.read_exact(&mut path_bytes)?;
+
+    // Consume the trailing newline that terminates the record.
+    let mut newline = [0u8; 1];
+    reader.read_exact(&mut newline)?;
+    if newline[0] != b'\n' {
+        return Err(truncated_manifest_error());
+    }
+
+    Ok(Some(ManifestEntry {
+        path: bytes_to_path(path_bytes),
+        size,
+        mtime: parse_mtime(&String::from_utf8_lossy(&mtime)),
+    }))
+}
+
+/// Parse one `\t`-delimited field as ASCII, failing loudly instead of silently
+/// defaulting: a corrupt `path_len` would otherwise desync every read that follows it
+/// (truncating the path to the wrong number of bytes), and a corrupt `size` would
+/// misreport a file as `Modified`/`Unchanged` with no diagnostic -- both unacceptable
+/// for a user-supplied `--diff` manifest, which we treat as untrusted input.
+fn parse_ascii<T: std::str::FromStr>(bytes: &[u8], field: &str) -> io::Result<T> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| corrupt_manifest_error(field, bytes))
+}
+
+fn corrupt_manifest_error(field: &str, bytes: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "corrupt snapshot manifest entry: invalid {field} {:?}",
+            String::from_utf8_lossy(bytes)
+        ),
+    )
+}
+
+fn parse_mtime(raw: &str) -> SystemTime {
+    let mut parts = raw.splitn(2, '.');
+    let secs: u64 = parts.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+    let nanos: u32 = parts.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+    SystemTime::UNIX_EPOCH + Duration::new(secs, nanos)
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn metadata_matches(metadata: &Metadata, manifest: &ManifestEntry) -> bool {
+    metadata.len() == manifest.size
+        && metadata
+            .modified()
+            .map(|mtime| mtime == manifest.mtime)
+            .unwrap_or(false)
+}
+
+/// Walk `disk` (entries from the live filesystem, in path order) and `manifest` (a
+/// previously saved, path-sorted snapshot) in lockstep via a merge-join, classifying
+/// every path as [`DiffStatus::Added`], [`DiffStatus::Removed`], [`DiffStatus::Modified`]
+/// or [`DiffStatus::Unchanged`].
+///
+/// Both iterators must yield entries in the same path order -- `Path`'s component-wise
+/// ordering, which is what `Ord for DirEntry` already sorts by (not a byte-wise order:
+/// `a/b` sorts before `a-b`). In debug builds, a regression in either side's
+/// ordering trips a `debug_assert!` instead of silently producing bogus Added/Removed
+/// noise; release builds are not re-checked per pair for performance.
+pub fn diff<'d>(
+    disk: impl Iterator<Item = &'d DirEntry> + 'd,
+    manifest: impl Iterator<Item = ManifestEntry> + 'd,
+) -> impl Iterator<Item = DiffEntry> + 'd {
+    MergeJoinDiff {
+        disk: disk.peekable(),
+        manifest: manifest.peekable(),
+        #[cfg(debug_assertions)]
+        last_disk_path: None,
+        #[cfg(debug_assertions)]
+        last_manifest_path: None,
+    }
+}
+
+struct MergeJoinDiff<D: Iterator, M: Iterator> {
+    disk: std::iter::Peekable<D>,
+    manifest: std::iter::Peekable<M>,
+    #[cfg(debug_assertions)]
+    last_disk_path: Option<PathBuf>,
+    #[cfg(debug_assertions)]
+    last_manifest_path: Option<PathBuf>,
+}
+
+impl<'d, D, M> MergeJoinDiff<D, M>
+where
+    D: Iterator<Item = &'d DirEntry>,
+    M: Iterator<Item = ManifestEntry>,
+{
+    fn next_disk(&mut self) -> &'d DirEntry {
+        let entry = self.disk.next().expect("peeked Some");
+        #[cfg(debug_assertions)]
+        {
+            if let Some(last) = &self.last_disk_path {
+                debug_assert!(
+                    last.as_path() <= entry.path(),
+                    "snapshot_diff::diff requires the disk walk to be sorted by path"
+                );
+            }
+            self.last_disk_path = Some(entry.path().to_path_buf());
+        }
+        entry
+    }
+
+    fn next_manifest(&mut self) -> ManifestEntry {
+        let entry = self.manifest.next().expect("peeked Some");
+        #[cfg(debug_assertions)]
+        {
+            if let Some(last) = &self.last_manifest_path {
+                debug_assert!(
+                    *last <= entry.path,
+                    "snapshot_diff::diff requires the manifest to be sorted by path"
+                );
+            }
+            self.last_manifest_path = Some(entry.path.clone());
+        }
+        entry
+    }
+}
+
+impl<'d, D, M> Iterator for MergeJoinDiff<D, M>
+where
+    D: Iterator<Item = &'d DirEntry>,
+    M: Iterator<Item = ManifestEntry>,
+{
+    type Item = DiffEntry;
+
+    fn next(&mut self) -> Option<DiffEntry> {
+        match (self.disk.peek(), self.manifest.peek()) {
+            (Some(d), Some(m)) => match d.path().cmp(&m.path) {
+                Ordering::Equal => {
+                    let d = self.next_disk();
+                    let m = self.next_manifest();
+                    let status = match d.metadata() {
+                        Some(metadata) if metadata_matches(metadata, &m) => DiffStatus::Unchanged,
+                        _ => DiffStatus::Modified,
+                    };
+                    Some(DiffEntry {
+                        path: d.path().to_path_buf(),
+                        status,
+                    })
+                }
+                Ordering::Less => Some(DiffEntry {
+                    path: self.next_disk().path().to_path_buf(),
+                    status: DiffStatus::Added,
+                }),
+                Ordering::Greater => Some(DiffEntry {
+                    path: self.next_manifest().path,
+                    status: DiffStatus::Removed,
+                }),
+            },
+            (Some(_), None) => Some(DiffEntry {
+                path: self.next_disk().path().to_path_buf(),
+                status: DiffStatus::Added,
+            }),
+            (None, Some(_)) => Some(DiffEntry {
+                path: self.next_manifest().path,
+                status: DiffStatus::Removed,
+            }),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest_entry(path: &str, size: u64, mtime: SystemTime) -> ManifestEntry {
+        ManifestEntry {
+            path: PathBuf::from(path),
+            size,
+            mtime,
+        }
+    }
+
+    #[test]
+    fn manifest_round_trips_through_write_and_read() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-snapshot-diff-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file_a = dir.join("a.txt");
+        let file_b = dir.join("weird\tname\nwith-tab-and-newline");
+        std::fs::write(&file_a, b"hello").unwrap();
+        std::fs::write(&file_b, b"world!").unwrap();
+
+        let walk_entries = [
+            DirEntry::broken_symlink(file_a.clone()),
+            DirEntry::broken_symlink(file_b.clone()),
+        ];
+
+        let manifest_path = dir.join("manifest.snapshot");
+        let mut out = std::fs::File::create(&manifest_path).unwrap();
+        write_manifest(walk_entries.iter(), &mut out).unwrap();
+        drop(out);
+
+        let read_back = read_manifest(&manifest_path).unwrap();
+        assert_eq!(read_back.len(), 2);
+        let paths: Vec<_> = read_back.iter().map(|e| e.path.clone()).collect();
+        assert!(paths.contains(&file_a));
+        assert!(paths.contains(&file_b));
+
+        let by_path_b = read_back.iter().find(|e| e.path == file_b).unwrap();
+        assert_eq!(by_path_b.size, 6);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_classifies_added_removed_unchanged_and_modified() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-snapshot-diff-test-classify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let unchanged_path = dir.join("unchanged.txt");
+        let modified_path = dir.join("modified.txt");
+        let added_path = dir.join("added.txt");
+        std::fs::write(&unchanged_path, b"same").unwrap();
+        std::fs::write(&modified_path, b"new-content").unwrap();
+        std::fs::write(&added_path, b"added").unwrap();
+
+        let unchanged_entry = DirEntry::broken_symlink(unchanged_path.clone());
+        let unchanged_mtime = unchanged_entry.metadata().unwrap().modified().unwrap();
+        let unchanged_size = unchanged_entry.metadata().unwrap().len();
+
+        let disk_entries = vec![
+            DirEntry::broken_symlink(added_path.clone()),
+            unchanged_entry,
+            DirEntry::broken_symlink(modified_path.clone()),
+        ];
+        let mut disk_entries = disk_entries;
+        disk_entries.sort();
+
+        let removed_path = dir.join("removed-only-in-manifest.txt");
+        let manifest = vec![
+            manifest_entry(modified_path.to_str().unwrap(), 0, SystemTime::UNIX_EPOCH),
+            manifest_entry(
+                removed_path.to_str().unwrap(),
+                1,
+                SystemTime::UNIX_EPOCH,
+            ),
+            manifest_entry(
+                unchanged_path.to_str().unwrap(),
+                unchanged_size,
+                unchanged_mtime,
+            ),
+        ];
+        let mut manifest = manifest;
+        manifest.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let results: Vec<_> = diff(disk_entries.iter(), manifest.into_iter()).collect();
+        let status_for = |path: &Path| {
+            results
+                .iter()
+                .find(|e| e.path == path)
+                .unwrap_or_else(|| panic!("missing diff entry for {path:?}"))
+                .status
+        };
+
+        assert_eq!(status_for(&added_path), DiffStatus::Added);
+        assert_eq!(status_for(&removed_path), DiffStatus::Removed);
+        assert_eq!(status_for(&modified_path), DiffStatus::Modified);
+        assert_eq!(status_for(&unchanged_path), DiffStatus::Unchanged);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted by path")]
+    fn diff_panics_in_debug_on_unsorted_manifest() {
+        let a = manifest_entry("b.txt", 0, SystemTime::UNIX_EPOCH);
+        let b = manifest_entry("a.txt", 0, SystemTime::UNIX_EPOCH);
+        let disk_entries: Vec<DirEntry> = Vec::new();
+        let _: Vec<_> = diff(disk_entries.iter(), vec![a, b].into_iter()).collect();
+    }
+
+    #[test]
+    fn read_manifest_rejects_a_corrupt_size_field_instead_of_zeroing_it() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-snapshot-diff-test-corrupt-size-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let manifest_path = dir.join("manifest.snapshot");
+        std::fs::write(&manifest_path, b"not-a-number\t0.0\t5\tfoo\n").unwrap();
+
+        let err = read_manifest(&manifest_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_manifest_rejects_a_corrupt_path_len_field_instead_of_zeroing_it() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fd-snapshot-diff-test-corrupt-path-len-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let manifest_path = dir.join("manifest.snapshot");
+        std::fs::write(&manifest_path, b"5\t0.0\tnot-a-number\tfoo\n").unwrap();
+
+        let err = read_manifest(&manifest_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}